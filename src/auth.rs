@@ -0,0 +1,149 @@
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{Error, HttpResponse};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use std::rc::Rc;
+
+/// Gates requests behind a bearer token. When no `secret` is configured this is a
+/// no-op, so existing token-less setups keep serving files openly.
+#[derive(Clone)]
+pub struct TokenAuth {
+    secret: Option<Rc<str>>,
+}
+
+impl TokenAuth {
+    pub fn new(secret: Option<String>) -> Self {
+        Self {
+            secret: secret.map(Rc::from),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for TokenAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = TokenAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(TokenAuthMiddleware {
+            service,
+            secret: self.secret.clone(),
+        })
+    }
+}
+
+pub struct TokenAuthMiddleware<S> {
+    service: S,
+    secret: Option<Rc<str>>,
+}
+
+impl<S, B> Service<ServiceRequest> for TokenAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let authorized = match &self.secret {
+            Some(secret) => is_authorized(&req, secret),
+            None => true,
+        };
+
+        if authorized {
+            let fut = self.service.call(req);
+            Box::pin(async move { Ok(fut.await?.map_into_left_body()) })
+        } else {
+            let response = HttpResponse::Unauthorized().finish().map_into_right_body();
+            Box::pin(async move { Ok(req.into_response(response)) })
+        }
+    }
+}
+
+fn is_authorized(req: &ServiceRequest, secret: &str) -> bool {
+    let bearer_ok = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| constant_time_eq(token, secret));
+
+    bearer_ok
+        || req
+            .query_string()
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .any(|(key, value)| key == "token" && constant_time_eq(value, secret))
+}
+
+/// Compares two strings without short-circuiting on the first mismatching byte, so a timing
+/// attacker can't use response latency to recover the configured token one byte at a time.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.bytes()
+        .zip(b.bytes())
+        .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+        == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn bearer_header_with_matching_token_is_authorized() {
+        let req = TestRequest::get()
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_srv_request();
+
+        assert!(is_authorized(&req, "secret"));
+    }
+
+    #[test]
+    fn bearer_header_with_wrong_token_is_unauthorized() {
+        let req = TestRequest::get()
+            .insert_header(("Authorization", "Bearer wrong"))
+            .to_srv_request();
+
+        assert!(!is_authorized(&req, "secret"));
+    }
+
+    #[test]
+    fn query_param_with_matching_token_is_authorized() {
+        let req = TestRequest::get()
+            .uri("/channel.m3u8?token=secret")
+            .to_srv_request();
+
+        assert!(is_authorized(&req, "secret"));
+    }
+
+    #[test]
+    fn no_token_at_all_is_unauthorized() {
+        let req = TestRequest::get().to_srv_request();
+
+        assert!(!is_authorized(&req, "secret"));
+    }
+
+    #[test]
+    fn constant_time_eq_matches_string_equality() {
+        assert!(constant_time_eq("abc", "abc"));
+        assert!(!constant_time_eq("abc", "abd"));
+        assert!(!constant_time_eq("abc", "ab"));
+        assert!(constant_time_eq("", ""));
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::{env, fs};
+
+fn default_refresh_time() -> String {
+    "05:30:00".to_string()
+}
+
+fn default_bind_address() -> String {
+    "127.0.0.1:5050".to_string()
+}
+
+fn default_serve_dir() -> String {
+    "./ilovetv_cache".to_string()
+}
+
+fn default_retry_count() -> usize {
+    10
+}
+
+fn default_max_age_days() -> i64 {
+    3
+}
+
+/// Which kind of file a [`Source`] points at, and therefore how it's handled
+/// after a successful download (e.g. only M3U sources feed the channel index).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceType {
+    M3u,
+    XmlTv,
+}
+
+/// One playlist or guide to fetch and cache.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Source {
+    pub name: String,
+    pub url: String,
+    pub file_name: String,
+    #[serde(rename = "type")]
+    pub source_type: SourceType,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default = "default_refresh_time")]
+    pub refresh_time: String,
+    #[serde(default = "default_bind_address")]
+    pub bind_address: String,
+    #[serde(default = "default_serve_dir")]
+    pub serve_dir: String,
+    #[serde(default = "default_retry_count")]
+    pub retry_count: usize,
+    #[serde(default = "default_max_age_days")]
+    pub max_age_days: i64,
+    #[serde(default)]
+    pub sources: Vec<Source>,
+    /// Bearer token required to access served files. `None` serves openly.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            refresh_time: default_refresh_time(),
+            bind_address: default_bind_address(),
+            serve_dir: default_serve_dir(),
+            retry_count: default_retry_count(),
+            max_age_days: default_max_age_days(),
+            sources: Vec::new(),
+            token: None,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file from the platform config directory if present,
+    /// otherwise falls back to the legacy `$M3U`/`$XML_TV` env vars, then to defaults.
+    pub fn load() -> Result<Self> {
+        if let Some(path) = Self::config_file_path() {
+            if let Some(config) = Self::read_file(&path)? {
+                return Ok(config);
+            }
+        }
+
+        Ok(Self::from_env())
+    }
+
+    fn config_file_path() -> Option<PathBuf> {
+        let dirs = ProjectDirs::from("", "", "ilovetv_cache")?;
+        let config_dir = dirs.config_dir();
+        for candidate in ["config.toml", "config.json"] {
+            let path = config_dir.join(candidate);
+            if path.is_file() {
+                return Some(path);
+            }
+        }
+        None
+    }
+
+    fn read_file(path: &PathBuf) -> Result<Option<Self>> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let config = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&content)
+                .with_context(|| format!("parsing {}", path.display()))?,
+            _ => toml::from_str(&content).with_context(|| format!("parsing {}", path.display()))?,
+        };
+        Ok(Some(config))
+    }
+
+    fn from_env() -> Self {
+        let mut sources = Vec::new();
+        if let Ok(m3u) = env::var("M3U") {
+            sources.push(Source {
+                name: "default".to_string(),
+                url: m3u,
+                file_name: "ilovetv.m3u".to_string(),
+                source_type: SourceType::M3u,
+            });
+        } else {
+            eprintln!("$M3U not found");
+        }
+        if let Ok(xml_tv) = env::var("XML_TV") {
+            sources.push(Source {
+                name: "default".to_string(),
+                url: xml_tv,
+                file_name: "xmltv.xml".to_string(),
+                source_type: SourceType::XmlTv,
+            });
+        } else {
+            eprintln!("$XML_TV not found, proceeding without...");
+        }
+
+        Self {
+            refresh_time: env::var("REFRESH_TIME").unwrap_or_else(|_| default_refresh_time()),
+            bind_address: env::var("SERVER_ADDR").unwrap_or_else(|_| default_bind_address()),
+            serve_dir: env::var("SERVE_DIR").unwrap_or_else(|_| default_serve_dir()),
+            retry_count: env::var("RETRY_COUNT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_else(default_retry_count),
+            max_age_days: env::var("MAX_AGE_DAYS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_else(default_max_age_days),
+            sources,
+            token: env::var("AUTH_TOKEN").ok(),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use serde::Serialize;
+
+/// A single entry parsed out of an `#EXTINF` line and the URL line that follows it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Channel {
+    pub name: String,
+    pub tvg_id: String,
+    pub group_title: String,
+    pub logo: String,
+    pub url: String,
+}
+
+/// Parses the contents of an M3U playlist into a list of [`Channel`]s.
+///
+/// Lines that don't fit the `#EXTINF` + URL pattern are skipped.
+pub fn parse(content: &str) -> Vec<Channel> {
+    let mut channels = Vec::new();
+    let mut lines = content.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        let line = line.trim();
+        let Some(extinf) = line.strip_prefix("#EXTINF:") else {
+            continue;
+        };
+
+        // The duration and the attributes come before the first comma; everything after it is
+        // the free-form name, which may itself contain commas (e.g. "Team A, Team B"). An
+        // attribute value that contains a comma moves the split into that attribute.
+        let name = extinf
+            .split_once(',')
+            .map(|(_, name)| name.trim().to_string())
+            .unwrap_or_default();
+
+        let tvg_id = extract_attr(extinf, "tvg-id");
+        let group_title = extract_attr(extinf, "group-title");
+        let logo = extract_attr(extinf, "tvg-logo");
+
+        let url = loop {
+            match lines.peek() {
+                Some(next) if next.trim().is_empty() => {
+                    lines.next();
+                }
+                Some(next) if !next.trim().starts_with('#') => {
+                    break Some(lines.next().unwrap().trim().to_string());
+                }
+                _ => break None,
+            }
+        };
+
+        if let Some(url) = url {
+            channels.push(Channel {
+                name,
+                tvg_id,
+                group_title,
+                logo,
+                url,
+            });
+        }
+    }
+
+    channels
+}
+
+fn extract_attr(extinf: &str, attr: &str) -> String {
+    let needle = format!("{}=\"", attr);
+    let Some(start) = extinf.find(&needle) else {
+        return String::new();
+    };
+    let rest = &extinf[start + needle.len()..];
+    rest.split_once('"')
+        .map(|(value, _)| value.to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_name_containing_a_comma() {
+        let content = "#EXTM3U\n\
+             #EXTINF:-1 tvg-id=\"ch1\" tvg-logo=\"logo.png\" group-title=\"Sports\",Team A, Team B\n\
+             http://example.com/stream.m3u8\n";
+
+        let channels = parse(content);
+
+        assert_eq!(channels.len(), 1);
+        assert_eq!(channels[0].name, "Team A, Team B");
+        assert_eq!(channels[0].tvg_id, "ch1");
+        assert_eq!(channels[0].group_title, "Sports");
+        assert_eq!(channels[0].logo, "logo.png");
+        assert_eq!(channels[0].url, "http://example.com/stream.m3u8");
+    }
+
+    #[test]
+    fn missing_attributes_default_to_empty() {
+        let content = "#EXTINF:-1,Plain Channel\nhttp://example.com/plain.m3u8\n";
+
+        let channels = parse(content);
+
+        assert_eq!(channels.len(), 1);
+        assert_eq!(channels[0].name, "Plain Channel");
+        assert_eq!(channels[0].tvg_id, "");
+        assert_eq!(channels[0].group_title, "");
+        assert_eq!(channels[0].logo, "");
+    }
+
+    #[test]
+    fn parses_multiple_entries_in_order() {
+        let content = concat!(
+            "#EXTM3U\n",
+            "#EXTINF:-1 tvg-id=\"a\",Channel A\n",
+            "http://example.com/a.m3u8\n",
+            "#EXTINF:-1 tvg-id=\"b\",Channel B\n",
+            "http://example.com/b.m3u8\n",
+        );
+
+        let channels = parse(content);
+
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].name, "Channel A");
+        assert_eq!(channels[1].name, "Channel B");
+    }
+
+    #[test]
+    fn extinf_without_a_following_url_is_skipped() {
+        let content =
+            "#EXTINF:-1,Orphan Channel\n#EXTINF:-1,Next Channel\nhttp://example.com/next.m3u8\n";
+
+        let channels = parse(content);
+
+        assert_eq!(channels.len(), 1);
+        assert_eq!(channels[0].name, "Next Channel");
+    }
+
+    #[test]
+    fn extract_attr_finds_a_quoted_value() {
+        assert_eq!(
+            extract_attr("tvg-id=\"abc\" group-title=\"x\"", "tvg-id"),
+            "abc"
+        );
+    }
+
+    #[test]
+    fn extract_attr_returns_empty_when_missing() {
+        assert_eq!(extract_attr("tvg-id=\"abc\"", "group-title"), "");
+    }
+}
@@ -1,71 +1,155 @@
+mod auth;
+mod config;
+mod m3u;
+
 use actix_files::Files;
-use actix_web::{App, HttpServer};
+use actix_web::{get, web, App, HttpResponse, HttpServer};
 use anyhow::{Context, Error, Result};
+use auth::TokenAuth;
 use chrono::{self, Duration, Local, NaiveTime};
+use config::{Config, Source, SourceType};
 use dotenv;
 use futures::TryStreamExt;
-use reqwest::{self, Client};
+use indicatif::{ProgressBar, ProgressStyle};
+use m3u::Channel;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{self, Client, StatusCode};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::time::Duration as StdDuration;
-use std::{env, process};
-use strum::{Display, EnumIter, IntoEnumIterator};
-use tokio::fs::{self, File};
+use std::sync::Arc;
+use std::time::{Duration as StdDuration, SystemTime};
+use tokio::fs::{self, File, OpenOptions};
 use tokio::io::AsyncWriteExt;
+use tokio::sync::RwLock;
 use tokio::{join, time};
 
-const SERVE_DIR: &'static str = "./ilovetv_cache";
-const SERVER_ADDR: &'static str = "127.0.0.1:5050";
 const USER_AGENT: &'static str = "ilovetv";
 
+/// `ETag`/`Last-Modified` response headers cached next to a served file so the
+/// next `refresh` can send a conditional GET instead of re-downloading blindly.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DownloadMeta {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl DownloadMeta {
+    async fn load(file_name: &str) -> Self {
+        match fs::read_to_string(format!("{}.meta", file_name)).await {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+            Err(_) => Self::default(),
+        }
+    }
+
+    async fn save(&self, file_name: &str) -> Result<()> {
+        let content = serde_json::to_string(self)?;
+        fs::write(format!("{}.meta", file_name), content).await?;
+        Ok(())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+enum DownloadOutcome {
+    Completed { pb: ProgressBar, meta: DownloadMeta },
+    NotModified,
+}
+
+/// Shared, queryable view of the channels parsed from each M3U source, keyed by source name.
+type ChannelIndex = Arc<RwLock<HashMap<String, Vec<Channel>>>>;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv::dotenv()?;
-    let _ = fs::create_dir(SERVE_DIR).await;
+    let config = Config::load()?;
+    let _ = fs::create_dir(&config.serve_dir).await;
     println!("Welcome to ilovetv cache!");
-    let ilovetv = ILoveTv::new();
+
+    let channels: ChannelIndex = Arc::new(RwLock::new(
+        load_channels(&config.serve_dir, &config.sources).await,
+    ));
+    let bind_address = config.bind_address.clone();
+    let serve_dir = config.serve_dir.clone();
+    let token = config.token.clone();
+    if token.is_some() {
+        println!("Serving cache with bearer token protection");
+    }
+    let ilovetv = ILoveTv::new(config, channels.clone());
     let ilovetv_daemon = ilovetv.daemonize();
 
-    let http_server =
-        HttpServer::new(move || App::new().service(Files::new("/", SERVE_DIR).prefer_utf8(true)))
-            .bind(SERVER_ADDR)?
-            .run();
-    println!("Serving cache on {}", SERVER_ADDR);
+    let http_server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(channels.clone()))
+            .service(
+                web::scope("")
+                    .wrap(TokenAuth::new(token.clone()))
+                    .service(search)
+                    .service(Files::new("/", &serve_dir).prefer_utf8(true)),
+            )
+    })
+    .bind(&bind_address)?
+    .run();
+    println!("Serving cache on {}", bind_address);
 
     let _ = join!(http_server, ilovetv_daemon);
     Ok(())
 }
 
+async fn load_channels(serve_dir: &str, sources: &[Source]) -> HashMap<String, Vec<Channel>> {
+    let mut channels = HashMap::new();
+    for source in sources.iter().filter(|s| s.source_type == SourceType::M3u) {
+        if let Ok(content) = fs::read_to_string(format!("{}/{}", serve_dir, source.file_name)).await
+        {
+            channels.insert(source.name.clone(), m3u::parse(&content));
+        }
+    }
+    channels
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: String,
+}
+
+#[get("/api/search")]
+async fn search(data: web::Data<ChannelIndex>, query: web::Query<SearchQuery>) -> HttpResponse {
+    let needle = query.q.to_lowercase();
+    let channels = data.read().await;
+    let matches: Vec<&Channel> = channels
+        .values()
+        .flatten()
+        .filter(|c| {
+            c.name.to_lowercase().contains(&needle) || c.tvg_id.to_lowercase().contains(&needle)
+        })
+        .collect();
+
+    HttpResponse::Ok().json(matches)
+}
+
 struct ILoveTv {
-    m3u: Box<str>,
-    xml_tv: Option<Box<str>>,
+    config: Config,
     client: Client,
+    channels: ChannelIndex,
 }
 
 impl ILoveTv {
-    fn new() -> Self {
-        let mut vars = env::vars()
-            .map(|(k, v)| (k, v.into_boxed_str()))
-            .collect::<HashMap<_, _>>();
-        let m3u = if let Some(m3u) = vars.remove("M3U") {
-            m3u
-        } else {
-            eprintln!("$M3U not found");
-            process::exit(0);
-        };
-        let xml_tv = vars.remove("XML_TV");
-        if xml_tv.is_none() {
-            eprintln!("$XML_TV not found, proceeding without...");
+    fn new(config: Config, channels: ChannelIndex) -> Self {
+        if config.sources.is_empty() {
+            eprintln!("No sources configured, nothing will be downloaded");
         }
 
         Self {
-            m3u,
-            xml_tv,
+            config,
             client: Client::new(),
+            channels,
         }
     }
 
     async fn daemonize(&self) {
-        let target_time = NaiveTime::from_hms_opt(5, 30, 0).unwrap();
+        let target_time = NaiveTime::parse_from_str(&self.config.refresh_time, "%H:%M:%S")
+            .unwrap_or_else(|_| NaiveTime::from_hms_opt(5, 30, 0).unwrap());
         let current_time = Local::now().time();
         if current_time < NaiveTime::from_hms_opt(19, 00, 00).unwrap() {
             self.refresh_loop(0).await;
@@ -76,10 +160,10 @@ impl ILoveTv {
             let duration_till_target = target_time - current_time;
 
             let sleep_duration = if duration_till_target.num_seconds() >= 0 {
-                // If it's before 5:30 AM, use the difference between 5:30 AM and the current time
+                // If it's before the target time, use the difference between it and the current time
                 duration_till_target
             } else {
-                // If it's after 5:30 AM, add 24 hours to get the sleep duration for the next day
+                // If it's after the target time, add 24 hours to get the sleep duration for the next day
                 duration_till_target + Duration::days(1)
             };
 
@@ -94,70 +178,169 @@ impl ILoveTv {
             );
 
             time::sleep(sleep_duration).await;
-            self.refresh_loop(10).await;
+            self.refresh_loop(self.config.retry_count).await;
         }
     }
 
     async fn refresh_loop(&self, retry: usize) {
-        for link_type in LinkType::iter() {
-            let link_name = link_type.to_string();
-            println!("Downloading {}", &link_name);
+        for source in &self.config.sources {
+            println!("Downloading {}", &source.name);
 
-            let mut status = self.refresh(&link_type).await;
+            let mut status = self.refresh(source).await;
             let mut counter = 1;
             while status.is_err() && counter <= retry {
                 println!(
-                    "Failed to download ({}/10) {}, will sleep 30 seconds",
-                    counter, &link_name
+                    "Failed to download ({}/{}) {}, will sleep 30 seconds",
+                    counter, retry, &source.name
                 );
                 time::sleep(StdDuration::from_secs(30)).await;
 
-                status = self.refresh(&link_type).await;
+                status = self.refresh(source).await;
                 counter += 1;
             }
         }
     }
 
-    async fn refresh(&self, link_type: &LinkType) -> Result<()> {
-        let (link, file_name) = match link_type {
-            LinkType::M3U => (Some(&self.m3u), "ilovetv.m3u"),
-            LinkType::XmlTv => (self.xml_tv.as_ref(), "xmltv.xml"),
-        };
-        let link = if let Some(l) = link { l } else { return Ok(()) };
+    async fn refresh(&self, source: &Source) -> Result<()> {
+        let serve_dir = &self.config.serve_dir;
+        let final_path = format!("{}/{}", serve_dir, source.file_name);
+        let max_age =
+            StdDuration::from_secs((self.config.max_age_days.max(0) as u64) * 24 * 60 * 60);
+        if let Ok(age) = file_age(&final_path).await {
+            if age < max_age {
+                println!(
+                    "{} is younger than max age, skipping fetch",
+                    source.file_name
+                );
+                return Ok(());
+            }
+        }
 
-        let (beginning, file_ext) = file_name.rsplit_once('.').context("Malformed filename")?;
+        let (beginning, file_ext) = source
+            .file_name
+            .rsplit_once('.')
+            .context("Malformed filename")?;
         let tmp_file_name = format!("{}-temp.{}", beginning, file_ext);
-        println!("save to: {}", format!("{}/{}", SERVE_DIR, &tmp_file_name));
+        let tmp_path = format!("{}/{}", serve_dir, &tmp_file_name);
+        println!("save to: {}", tmp_path);
 
+        let download_meta = DownloadMeta::load(&final_path).await;
         let status = self
-            .save_to_file(link, &format!("{}/{}", SERVE_DIR, &tmp_file_name))
+            .save_to_file(&source.url, &tmp_path, &download_meta)
             .await;
-        if let Err(e) = status {
-            eprintln!("Error occured downloading '{}', {:?}", link, e);
-        }
 
-        let name = (
-            format!("{}/{}", SERVE_DIR, tmp_file_name),
-            format!("{}/{}", SERVE_DIR, file_name),
-        );
+        let (pb, meta) = match status {
+            Ok(DownloadOutcome::NotModified) => {
+                let _ = fs::remove_file(&tmp_path).await;
+                println!("{} not modified, keeping cached copy", source.file_name);
+                return Ok(());
+            }
+            Ok(DownloadOutcome::Completed { pb, meta }) => (Some(pb), meta),
+            Err(e) => {
+                eprintln!("Error occured downloading '{}', {:?}", source.url, e);
+                let _ = fs::remove_file(&tmp_path).await;
+                return Err(e);
+            }
+        };
+
+        let name = (tmp_path, final_path);
         println!(
             "No errors on save file, will rename {} to {}",
             &name.0, &name.1
         );
-        fs::rename(name.0, name.1).await?;
-        println!("Refreshed {}", file_name);
+        fs::rename(name.0, &name.1).await?;
+        if let Some(pb) = pb {
+            pb.finish_with_message(format!("Refreshed {}", source.file_name));
+        } else {
+            println!("Refreshed {}", source.file_name);
+        }
+
+        if !meta.is_empty() {
+            if let Err(e) = meta.save(&name.1).await {
+                eprintln!(
+                    "Failed to persist download metadata for {}: {:?}",
+                    source.file_name, e
+                );
+            }
+        }
+
+        if source.source_type == SourceType::M3u {
+            if let Ok(content) = fs::read_to_string(&name.1).await {
+                self.channels
+                    .write()
+                    .await
+                    .insert(source.name.clone(), m3u::parse(&content));
+                println!("Re-indexed channels from {}", source.file_name);
+            }
+        }
 
         Ok(())
     }
 
-    async fn save_to_file(&self, link: &str, file_name: &str) -> Result<()> {
-        let response = self
+    async fn save_to_file(
+        &self,
+        link: &str,
+        file_name: &str,
+        conditional: &DownloadMeta,
+    ) -> Result<DownloadOutcome> {
+        let mut existing_len = fs::metadata(file_name)
+            .await
+            .map(|meta| meta.len())
+            .unwrap_or(0);
+
+        if existing_len > 0 && conditional.etag.is_none() && conditional.last_modified.is_none() {
+            // No ETag/Last-Modified to pin the partial download to a specific version of the
+            // resource, so a 206 can't be trusted to be a continuation of the same file.
+            println!(
+                "No stored ETag/Last-Modified for {}, discarding partial download and restarting",
+                file_name
+            );
+            fs::remove_file(file_name).await.ok();
+            existing_len = 0;
+        }
+
+        let mut request = self
             .client
             .get(link)
             .header("User-Agent", USER_AGENT)
-            .header("Accept", "*/*")
-            .send()
-            .await?;
+            .header("Accept", "*/*");
+        if existing_len > 0 {
+            println!("Resuming {} from byte {}", file_name, existing_len);
+            request = request.header(RANGE, format!("bytes={}-", existing_len));
+            // Ties the Range request to the validator stored for the last completed download of
+            // this file: if the resource no longer matches it, the server must ignore Range and
+            // send a fresh 200 instead of a 206.
+            if let Some(etag) = &conditional.etag {
+                request = request.header(IF_RANGE, etag);
+            } else if let Some(last_modified) = &conditional.last_modified {
+                request = request.header(IF_RANGE, last_modified);
+            }
+        } else {
+            if let Some(etag) = &conditional.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &conditional.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let response = request.send().await?;
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(DownloadOutcome::NotModified);
+        }
+
+        if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            println!("{} is already complete", file_name);
+            let pb = ProgressBar::new(existing_len);
+            pb.set_style(
+                ProgressStyle::with_template("{bar:40.cyan/blue} {bytes}/{total_bytes}").unwrap(),
+            );
+            pb.finish();
+            return Ok(DownloadOutcome::Completed {
+                pb,
+                meta: DownloadMeta::default(),
+            });
+        }
 
         if !response.status().is_success() {
             println!("Got status code {}", response.status().as_u16());
@@ -168,21 +351,86 @@ impl ILoveTv {
             )));
         }
 
-        let mut f = File::create(file_name).await?;
+        let response_meta = DownloadMeta {
+            etag: response
+                .headers()
+                .get(ETAG)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from),
+            last_modified: response
+                .headers()
+                .get(LAST_MODIFIED)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from),
+        };
+
+        let resuming = existing_len > 0 && response.status() == StatusCode::PARTIAL_CONTENT;
+        if existing_len > 0 && !resuming {
+            println!(
+                "Server ignored Range header, restarting {} from zero",
+                file_name
+            );
+        }
+
+        let total = response
+            .content_length()
+            .map(|len| if resuming { len + existing_len } else { len });
+
+        let pb = match total {
+            Some(total) => {
+                let pb = ProgressBar::new(total);
+                pb.set_style(
+                    ProgressStyle::with_template(
+                        "{bar:40.cyan/blue} {bytes}/{total_bytes} ({bytes_per_sec}, ETA {eta})",
+                    )
+                    .unwrap(),
+                );
+                pb
+            }
+            None => {
+                let pb = ProgressBar::new_spinner();
+                pb.set_style(
+                    ProgressStyle::with_template("{spinner} {bytes} downloaded ({bytes_per_sec})")
+                        .unwrap(),
+                );
+                pb
+            }
+        };
+        if resuming {
+            pb.inc(existing_len);
+        }
+
+        let mut f = if resuming {
+            OpenOptions::new().append(true).open(file_name).await?
+        } else {
+            File::create(file_name).await?
+        };
         println!("Created file");
         let mut stream = response.bytes_stream();
         println!("Got byte stream");
 
-        while let Ok(Some(item)) = stream.try_next().await {
-            f.write(&item).await?;
+        loop {
+            match stream.try_next().await {
+                Ok(Some(item)) => {
+                    pb.inc(item.len() as u64);
+                    f.write(&item).await?;
+                }
+                Ok(None) => break,
+                Err(e) => return Err(e.into()),
+            }
         }
 
-        Ok(())
+        Ok(DownloadOutcome::Completed {
+            pb,
+            meta: response_meta,
+        })
     }
 }
 
-#[derive(EnumIter, Display)]
-enum LinkType {
-    M3U,
-    XmlTv,
+/// Age of `path` since it was last modified, or an `Err` if it doesn't exist yet.
+async fn file_age(path: &str) -> Result<StdDuration> {
+    let modified = fs::metadata(path).await?.modified()?;
+    Ok(SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default())
 }